@@ -0,0 +1,526 @@
+//! Pluggable input decoders, selected by `--input-format`.
+//!
+//! siplog used to only understand one bunyan-ish JSON schema (`SipAppJson`)
+//! plus an ad-hoc heuristic for free-form text. `LineDecoder` generalizes
+//! that: each decoder tries to make sense of a raw line and, on success,
+//! returns the fields in the common `ParsedLine` shape that `format::render`
+//! knows how to draw from.
+
+use crate::format::LogFields;
+use chrono::{Local, NaiveDateTime};
+use log::Level;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+/// Fields gathered from a decoded line, regardless of which decoder produced
+/// them. Fields a given input shape doesn't carry (e.g. `pid` for a logfmt
+/// line with no `pid=` key) are left `None`.
+#[derive(Clone, Debug, Default)]
+pub struct ParsedLine {
+    pub level: Option<Level>,
+    pub timestamp: Option<String>,
+    pub source_line: Option<String>,
+    pub message: String,
+    pub pid: Option<usize>,
+    pub hostname: Option<String>,
+    pub type_: Option<String>,
+    pub stack: Option<String>,
+    pub errno: Option<String>,
+    pub syscall: Option<String>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub secret: Option<String>,
+    pub v: Option<usize>,
+}
+
+impl From<ParsedLine> for LogFields {
+    fn from(parsed: ParsedLine) -> Self {
+        LogFields {
+            level: Some(parsed.level.unwrap_or(Level::Info)),
+            timestamp: parsed
+                .timestamp
+                .unwrap_or_else(|| Local::now().format("%Y-%m-%d %H:%M:%S%.3f").to_string()),
+            source_line: parsed.source_line,
+            message: parsed.message,
+            pid: parsed.pid,
+            hostname: parsed.hostname,
+            type_: parsed.type_,
+            stack: parsed.stack,
+            errno: parsed.errno,
+            syscall: parsed.syscall,
+            address: parsed.address,
+            port: parsed.port,
+            secret: parsed.secret,
+            v: parsed.v,
+        }
+    }
+}
+
+/// Tries to make sense of one raw input line, returning `None` if the line
+/// doesn't look like this decoder's shape at all. `Send + Sync` so a set of
+/// decoders can be shared across the `--workers` worker pool.
+pub trait LineDecoder: Send + Sync {
+    fn try_decode(&self, line: &str) -> Option<ParsedLine>;
+}
+
+/// `--input-format` selection.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum InputFormat {
+    /// Try `sipapp`, then fall back to the free-text heuristic.
+    Auto,
+    SipApp,
+    Logfmt,
+    Json,
+}
+
+impl FromStr for InputFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.to_lowercase().as_str() {
+            "auto" => Ok(InputFormat::Auto),
+            "sipapp" => Ok(InputFormat::SipApp),
+            "logfmt" => Ok(InputFormat::Logfmt),
+            "json" => Ok(InputFormat::Json),
+            other => Err(format!(
+                "unknown --input-format `{}` (expected auto, sipapp, logfmt, or json)",
+                other
+            )),
+        }
+    }
+}
+
+/// Build the ordered list of decoders `--input-format` selects. `main`'s
+/// loop tries each in turn and renders the first success.
+pub fn build_decoders(format: InputFormat, field_map: &HashMap<String, String>) -> Vec<Box<dyn LineDecoder>> {
+    match format {
+        InputFormat::Auto => vec![Box::new(SipAppDecoder), Box::new(HeuristicDecoder)],
+        InputFormat::SipApp => vec![Box::new(SipAppDecoder)],
+        InputFormat::Logfmt => vec![Box::new(LogfmtDecoder)],
+        InputFormat::Json => vec![Box::new(JsonFieldMapDecoder::new(field_map))],
+    }
+}
+
+/// Parse a `--field-map` spec like `"level=severity,time=ts,msg=message"`
+/// into canonical-field -> source-key pairs for `JsonFieldMapDecoder`.
+pub fn parse_field_map(spec: &str) -> HashMap<String, String> {
+    spec.split(',')
+        .filter_map(|pair| {
+            let mut parts = pair.splitn(2, '=');
+            let canonical = parts.next()?.trim();
+            let source = parts.next()?.trim();
+            if canonical.is_empty() || source.is_empty() {
+                return None;
+            }
+            Some((canonical.to_string(), source.to_string()))
+        })
+        .collect()
+}
+
+fn format_epoch_millis(time: u64) -> String {
+    let seconds = (time / 1000) as i64;
+    let nanoseconds = (1_000_000 * (time % 1000)) as u32;
+    chrono::DateTime::from_timestamp(seconds, nanoseconds)
+        .unwrap_or_default()
+        .format("%Y-%m-%d %H:%M:%S%.3f")
+        .to_string()
+}
+
+/// A level name used by both the in-house sipapp schema (numeric bunyan
+/// levels) and the heuristic text decoder (bracketed level words).
+pub enum CustomLevel {
+    // these are identical to log::Level
+    Error,
+    Warn,
+    Info,
+    Debug,
+    Trace,
+    // these are new
+    Err,
+    Warning,
+    Console,
+    Notice,
+}
+
+impl From<usize> for CustomLevel {
+    fn from(item: usize) -> Self {
+        if item == 10 {
+            return CustomLevel::Trace;
+        }
+        if item == 20 {
+            return CustomLevel::Debug;
+        }
+        if item == 30 {
+            return CustomLevel::Info;
+        }
+        if item == 40 {
+            return CustomLevel::Warn;
+        }
+        if item == 50 {
+            return CustomLevel::Error;
+        }
+        if item == 60 {
+            return CustomLevel::Error;
+        }
+
+        CustomLevel::Trace
+    }
+}
+
+impl From<CustomLevel> for Level {
+    fn from(item: CustomLevel) -> Self {
+        match item {
+            // these are identical to log::Level
+            CustomLevel::Error => Level::Error,
+            CustomLevel::Warn => Level::Warn,
+            CustomLevel::Info => Level::Info,
+            CustomLevel::Debug => Level::Debug,
+            CustomLevel::Trace => Level::Trace,
+            // these are new
+            CustomLevel::Err => Level::Error,
+            CustomLevel::Warning => Level::Warn,
+            CustomLevel::Console => Level::Debug,
+            CustomLevel::Notice => Level::Trace,
+        }
+    }
+}
+
+impl TryFrom<String> for CustomLevel {
+    type Error = &'static str;
+
+    fn try_from(item: String) -> Result<Self, &'static str> {
+        // these are identical to log::Level
+        if item == "ERROR" {
+            return Ok(CustomLevel::Error);
+        }
+        if item == "WARN" {
+            return Ok(CustomLevel::Warn);
+        }
+        if item == "INFO" {
+            return Ok(CustomLevel::Info);
+        }
+        if item == "DEBUG" {
+            return Ok(CustomLevel::Debug);
+        }
+        if item == "TRACE" {
+            return Ok(CustomLevel::Trace);
+        }
+        // these are new
+        if item == "ERR" {
+            return Ok(CustomLevel::Err);
+        }
+        if item == "WARNING" {
+            return Ok(CustomLevel::Warn);
+        }
+        if item == "CONSOLE" {
+            return Ok(CustomLevel::Console);
+        }
+        if item == "NOTICE" {
+            return Ok(CustomLevel::Notice);
+        }
+
+        Err("no such leven indicator recognized")
+    }
+}
+
+/// The original in-house bunyan-ish schema.
+#[derive(Deserialize, Clone, Debug)]
+pub struct SipAppJson {
+    level: usize,
+    time: u64,
+    msg: String,
+    pid: usize,
+    hostname: String,
+    #[serde(rename = "type")]
+    type_: Option<String>,
+    stack: Option<String>,
+    errno: Option<String>,
+    syscall: Option<String>,
+    address: Option<String>,
+    port: Option<u16>,
+    secret: Option<String>,
+    v: usize,
+}
+
+impl SipAppJson {
+    fn into_parsed_line(self) -> ParsedLine {
+        ParsedLine {
+            level: Some(Level::from(CustomLevel::from(self.level))),
+            timestamp: Some(format_epoch_millis(self.time)),
+            source_line: None,
+            message: self.msg.trim().to_string(),
+            pid: Some(self.pid),
+            hostname: Some(self.hostname.trim().to_string()),
+            type_: self.type_.map(|s| s.trim().to_string()),
+            stack: self.stack.map(|s| s.trim().to_string()),
+            errno: self.errno.map(|s| s.trim().to_string()),
+            syscall: self.syscall.map(|s| s.trim().to_string()),
+            address: self.address.map(|s| s.trim().to_string()),
+            port: self.port,
+            secret: self.secret.map(|s| s.trim().to_string()),
+            v: Some(self.v),
+        }
+    }
+}
+
+pub struct SipAppDecoder;
+
+impl LineDecoder for SipAppDecoder {
+    fn try_decode(&self, line: &str) -> Option<ParsedLine> {
+        let parsed: SipAppJson = serde_json::from_str(line).ok()?;
+        Some(parsed.into_parsed_line())
+    }
+}
+
+/// `key=value key="quoted value"` lines, e.g. what `env_logger` or Heroku's
+/// router emit. Only the well-known keys are mapped into `ParsedLine`;
+/// anything else is ignored.
+pub struct LogfmtDecoder;
+
+fn parse_logfmt_pairs(line: &str) -> HashMap<String, String> {
+    let mut tokens = Vec::new();
+    let mut current = String::new();
+    let mut in_quotes = false;
+    for c in line.chars() {
+        match c {
+            '"' => {
+                in_quotes = !in_quotes;
+                current.push(c);
+            }
+            ' ' if !in_quotes => {
+                if !current.is_empty() {
+                    tokens.push(std::mem::take(&mut current));
+                }
+            }
+            _ => current.push(c),
+        }
+    }
+    if !current.is_empty() {
+        tokens.push(current);
+    }
+
+    let mut pairs = HashMap::new();
+    for token in tokens {
+        if let Some(eq_idx) = token.find('=') {
+            let key = token[..eq_idx].to_string();
+            let mut value = &token[eq_idx + 1..];
+            if value.len() >= 2 && value.starts_with('"') && value.ends_with('"') {
+                value = &value[1..value.len() - 1];
+            }
+            pairs.insert(key, value.to_string());
+        }
+    }
+    pairs
+}
+
+impl LineDecoder for LogfmtDecoder {
+    fn try_decode(&self, line: &str) -> Option<ParsedLine> {
+        let pairs = parse_logfmt_pairs(line);
+        if pairs.is_empty() {
+            return None;
+        }
+
+        Some(ParsedLine {
+            level: pairs
+                .get("level")
+                .and_then(|v| CustomLevel::try_from(v.to_uppercase()).ok())
+                .map(Level::from),
+            timestamp: pairs.get("ts").cloned(),
+            message: pairs.get("msg").cloned().unwrap_or_default(),
+            pid: pairs.get("pid").and_then(|v| v.parse::<usize>().ok()),
+            ..Default::default()
+        })
+    }
+}
+
+/// A generic JSON decoder for apps whose field names differ from
+/// `SipAppJson`'s, driven by `--field-map`. Any canonical field left
+/// unmapped falls back to a source key of the same name.
+pub struct JsonFieldMapDecoder {
+    field_map: HashMap<String, String>,
+}
+
+const CANONICAL_FIELDS: &[&str] = &[
+    "level", "time", "msg", "pid", "hostname", "type", "stack", "errno", "syscall", "address",
+    "port", "secret", "v",
+];
+
+impl JsonFieldMapDecoder {
+    pub fn new(field_map: &HashMap<String, String>) -> Self {
+        let resolved = CANONICAL_FIELDS
+            .iter()
+            .map(|name| {
+                let source = field_map.get(*name).cloned().unwrap_or_else(|| name.to_string());
+                (name.to_string(), source)
+            })
+            .collect();
+        JsonFieldMapDecoder { field_map: resolved }
+    }
+
+    fn get<'a>(&self, object: &'a serde_json::Map<String, serde_json::Value>, canonical: &str) -> Option<&'a serde_json::Value> {
+        object.get(self.field_map.get(canonical).map(String::as_str).unwrap_or(canonical))
+    }
+}
+
+impl LineDecoder for JsonFieldMapDecoder {
+    fn try_decode(&self, line: &str) -> Option<ParsedLine> {
+        let value: serde_json::Value = serde_json::from_str(line).ok()?;
+        let object = value.as_object()?;
+
+        let level = self.get(object, "level").and_then(|v| {
+            if let Some(n) = v.as_u64() {
+                Some(Level::from(CustomLevel::from(n as usize)))
+            } else {
+                v.as_str()
+                    .and_then(|s| CustomLevel::try_from(s.to_uppercase()).ok())
+                    .map(Level::from)
+            }
+        });
+
+        let timestamp = self.get(object, "time").and_then(|v| {
+            v.as_u64()
+                .map(format_epoch_millis)
+                .or_else(|| v.as_str().map(|s| s.to_string()))
+        });
+
+        let message = self
+            .get(object, "msg")
+            .and_then(|v| v.as_str())
+            .unwrap_or_default()
+            .trim()
+            .to_string();
+
+        let as_string = |v: Option<&serde_json::Value>| v.and_then(|v| v.as_str()).map(|s| s.trim().to_string());
+
+        Some(ParsedLine {
+            level,
+            timestamp,
+            source_line: None,
+            message,
+            pid: self.get(object, "pid").and_then(|v| v.as_u64()).map(|v| v as usize),
+            hostname: as_string(self.get(object, "hostname")),
+            type_: as_string(self.get(object, "type")),
+            stack: as_string(self.get(object, "stack")),
+            errno: as_string(self.get(object, "errno")),
+            syscall: as_string(self.get(object, "syscall")),
+            address: as_string(self.get(object, "address")),
+            port: self.get(object, "port").and_then(|v| v.as_u64()).map(|v| v as u16),
+            secret: as_string(self.get(object, "secret")),
+            v: self.get(object, "v").and_then(|v| v.as_u64()).map(|v| v as usize),
+        })
+    }
+}
+
+/// The original space-splitting heuristic, used as the `auto` fallback for
+/// free-text lines that aren't JSON.
+pub struct HeuristicDecoder;
+
+impl LineDecoder for HeuristicDecoder {
+    fn try_decode(&self, line: &str) -> Option<ParsedLine> {
+        Some(decode_heuristic(line))
+    }
+}
+
+fn decode_heuristic(line: &str) -> ParsedLine {
+    let mut split: Vec<&str> = line.split(' ').collect();
+
+    // search for source line
+    // assume source lines are of the format "/path/to/file:line_number" (potentially surrounded by brackets [])
+    let mut found_line: Option<String> = None;
+    for index in 0..split.len() {
+        let sub_split: Vec<&str> = split[index].split(':').collect();
+        if sub_split.len() != 2 {
+            continue;
+        }
+        let line_number = sub_split[1].to_string().replace(['[', ']'], "");
+        if line_number.parse::<i32>().is_ok() {
+            found_line = Some(split[index].to_string());
+            split.remove(index);
+            break;
+        }
+    }
+
+    // search for an indicator of a level
+    // empirically, these may be surrounded by brackets [] or colons :)
+    let mut found_level: Option<Level> = None;
+    for index in 0..split.len() {
+        let level_candidate = split[index].to_string().replace(['[', ']', ':'], "");
+        if let Ok(level_candidate) = CustomLevel::try_from(level_candidate) {
+            found_level = Some(Level::from(level_candidate));
+            split.remove(index);
+            break;
+        }
+    }
+
+    // search for a timestamp anywhere in the line (though it will usually be in the first two splits)
+    let mut found_timestamp: Option<NaiveDateTime> = None;
+    if split.len() >= 2 {
+        for index in 0..split.len() - 1 {
+            let day = split[index]
+                .to_string()
+                .replace(|c: char| !c.is_ascii(), "")
+                .replace(['[', ']'], "");
+            let hour = split[index + 1]
+                .to_string()
+                .replace(|c: char| !c.is_ascii(), "")
+                .replace(['[', ']'], "");
+            let timestamp_candidate = day + " " + &hour;
+            if let Ok(timestamp_candidate) =
+                NaiveDateTime::parse_from_str(&timestamp_candidate, "%Y-%m-%d %H:%M:%S%.3f")
+            {
+                found_timestamp = Some(timestamp_candidate);
+                split.remove(index + 1);
+                split.remove(index);
+                break;
+            }
+        }
+    }
+
+    let timestamp = found_timestamp.map(|ts| ts.format("%Y-%m-%d %H:%M:%S%.3f").to_string());
+
+    let mut message = String::new();
+    for str in split {
+        message.push_str(str);
+        message.push(' ');
+    }
+    let message = message.trim().to_string();
+
+    ParsedLine {
+        level: found_level,
+        timestamp,
+        source_line: found_line,
+        message,
+        ..Default::default()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn logfmt_pairs_handle_quoted_values_with_spaces() {
+        let pairs = parse_logfmt_pairs(r#"level=info msg="call ended" pid=123"#);
+        assert_eq!(pairs.get("level").map(String::as_str), Some("info"));
+        assert_eq!(pairs.get("msg").map(String::as_str), Some("call ended"));
+        assert_eq!(pairs.get("pid").map(String::as_str), Some("123"));
+    }
+
+    #[test]
+    fn logfmt_decoder_rejects_lines_with_no_pairs() {
+        assert!(LogfmtDecoder.try_decode("just some free text").is_none());
+    }
+
+    #[test]
+    fn field_map_resolves_mapped_and_falls_back_to_canonical_name() {
+        let field_map = parse_field_map("level=severity,msg=message");
+        let decoder = JsonFieldMapDecoder::new(&field_map);
+        let line = r#"{"severity":"ERROR","message":"boom","pid":7}"#;
+        let parsed = decoder.try_decode(line).expect("valid JSON object");
+        assert_eq!(parsed.level, Some(Level::Error));
+        assert_eq!(parsed.message, "boom");
+        assert_eq!(parsed.pid, Some(7));
+    }
+}
@@ -0,0 +1,120 @@
+//! Severity/pid/type filtering applied right after a line is parsed, so
+//! suppressed lines are skipped entirely instead of rendered and thrown away.
+
+use crate::logging::{self, Directive};
+use log::Level;
+
+/// The filtering criteria built from `--min-level`/`--filter`, `--pid`, and
+/// `--type`/`--tag`. A line is printed only if it passes all of the
+/// criteria that were actually supplied.
+#[derive(Clone, Debug, Default)]
+pub struct Filters {
+    pub min_level: Option<Level>,
+    pub pid: Option<usize>,
+    pub type_: Option<String>,
+    /// `--filter` directives (e.g. `default=info,rtp=debug`). When present,
+    /// a directive matching the line's `type_` (or its `default=` entry)
+    /// takes priority over `min_level`; lines whose type matches no
+    /// directive at all, including the `default=` one, still fall back to
+    /// `min_level`.
+    pub directives: Vec<Directive>,
+}
+
+impl Filters {
+    /// `level` is compared using the same ordering `CustomLevel`/`Level`
+    /// already use elsewhere (`Level::Error` is the most severe), so
+    /// `--min-level info` keeps `Error`/`Warn`/`Info` and drops `Debug`/`Trace`.
+    /// `--filter` directives take priority per-line when one applies, but
+    /// `min_level` is still consulted as a fallback rather than being
+    /// skipped outright.
+    pub fn allows(&self, level: Level, pid: Option<usize>, type_: Option<&str>) -> bool {
+        let directive_threshold = logging::effective_level(&self.directives, type_);
+        match directive_threshold.or_else(|| self.min_level.map(|level| level.to_level_filter())) {
+            Some(threshold) if level > threshold => return false,
+            _ => {}
+        }
+
+        if let Some(want_pid) = self.pid {
+            if pid != Some(want_pid) {
+                return false;
+            }
+        }
+
+        if let Some(want_type) = &self.type_ {
+            if type_ != Some(want_type.as_str()) {
+                return false;
+            }
+        }
+
+        true
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn min_level_drops_less_severe_lines() {
+        let filters = Filters {
+            min_level: Some(Level::Warn),
+            ..Default::default()
+        };
+        assert!(filters.allows(Level::Error, None, None));
+        assert!(filters.allows(Level::Warn, None, None));
+        assert!(!filters.allows(Level::Info, None, None));
+    }
+
+    #[test]
+    fn pid_and_type_must_match_when_set() {
+        let filters = Filters {
+            pid: Some(42),
+            type_: Some("sip".to_string()),
+            ..Default::default()
+        };
+        assert!(filters.allows(Level::Info, Some(42), Some("sip")));
+        assert!(!filters.allows(Level::Info, Some(7), Some("sip")));
+        assert!(!filters.allows(Level::Info, Some(42), Some("rtp")));
+    }
+
+    fn directive(target: Option<&str>, level: &str) -> Directive {
+        Directive {
+            target: target.map(str::to_string),
+            level: level.parse().unwrap(),
+        }
+    }
+
+    #[test]
+    fn directive_matching_the_type_overrides_min_level() {
+        let filters = Filters {
+            min_level: Some(Level::Warn),
+            directives: vec![directive(Some("rtp"), "debug")],
+            ..Default::default()
+        };
+        assert!(filters.allows(Level::Debug, None, Some("rtp")));
+    }
+
+    #[test]
+    fn directive_falls_back_to_default_entry() {
+        let filters = Filters {
+            directives: vec![directive(None, "info"), directive(Some("rtp"), "debug")],
+            ..Default::default()
+        };
+        assert!(filters.allows(Level::Info, None, Some("sip")));
+        assert!(!filters.allows(Level::Debug, None, Some("sip")));
+    }
+
+    #[test]
+    fn unmatched_type_falls_back_to_min_level_not_unfiltered() {
+        // Regression test: a --filter set with no default= entry and no
+        // directive for this line's type must still honor --min-level
+        // instead of silently letting every severity through.
+        let filters = Filters {
+            min_level: Some(Level::Warn),
+            directives: vec![directive(Some("rtp"), "debug")],
+            ..Default::default()
+        };
+        assert!(!filters.allows(Level::Trace, None, Some("sip")));
+        assert!(filters.allows(Level::Warn, None, Some("sip")));
+    }
+}
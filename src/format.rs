@@ -0,0 +1,393 @@
+//! User-defined output layouts.
+//!
+//! `--format` lets an operator control which fields show up in each printed
+//! line, and in what order, instead of being stuck with the hardcoded
+//! `[LEVEL timestamp] ...` layout. A template such as
+//! `"{timestamp} {level} {pid}@{hostname}: {msg}"` is parsed once at startup
+//! into a `Vec<LogSegment>`; `render` then walks that list for every line,
+//! regardless of whether the line came from the JSON decoder or the
+//! heuristic text path.
+//!
+//! `[...]` marks a conditional group: everything inside it (literal text and
+//! fields alike) is dropped as a unit unless at least one field inside would
+//! have rendered a value, so a template can bracket or label an optional
+//! field without leaving behind stray punctuation when that field is
+//! missing.
+
+use log::Level;
+use nom::branch::alt;
+use nom::bytes::complete::{take_till1, take_while1};
+use nom::character::complete::char;
+use nom::combinator::map;
+use nom::multi::many0;
+use nom::sequence::delimited;
+use nom::IResult;
+use std::fmt;
+
+/// The compact default layout used when `--format` is not given. Every
+/// optional field is wrapped in its own `[...]` group so lines missing that
+/// field (e.g. `source_line` for JSON input, or any of the "extras" for
+/// heuristic text) don't print stray empty punctuation for it.
+pub const DEFAULT_FORMAT: &str = "{timestamp} {level}[ {source_line}][ v:{v}][ pid:{pid}][ hostname:{hostname}][ type:{type}][ stack:{stack}][ errno:{errno}][ syscall:{syscall}][ address:{address}][ port:{port}][ secret:{secret}] {msg}";
+
+/// One piece of a rendered log line: either literal text taken verbatim from
+/// the template, a named field pulled from the line being printed, or a
+/// conditional group of segments that renders as a unit.
+#[derive(Clone, Debug, PartialEq)]
+pub enum LogSegment {
+    Literal(String),
+    /// A `[...]` group: rendered only if at least one field inside it has a
+    /// value, so surrounding literal text doesn't outlive the field it
+    /// decorates.
+    Group(Vec<LogSegment>),
+    Timestamp,
+    Level,
+    Pid,
+    Hostname,
+    Type,
+    SourceLine,
+    Message,
+    Stack,
+    Errno,
+    Syscall,
+    Address,
+    Port,
+    Secret,
+    V,
+}
+
+impl LogSegment {
+    fn from_field_name(name: &str) -> Result<Self, FormatError> {
+        Ok(match name {
+            "timestamp" => LogSegment::Timestamp,
+            "level" => LogSegment::Level,
+            "pid" => LogSegment::Pid,
+            "hostname" => LogSegment::Hostname,
+            "type" => LogSegment::Type,
+            "source_line" => LogSegment::SourceLine,
+            "msg" | "message" => LogSegment::Message,
+            "stack" => LogSegment::Stack,
+            "errno" => LogSegment::Errno,
+            "syscall" => LogSegment::Syscall,
+            "address" => LogSegment::Address,
+            "port" => LogSegment::Port,
+            "secret" => LogSegment::Secret,
+            "v" => LogSegment::V,
+            other => return Err(FormatError::UnknownField(other.to_string())),
+        })
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum FormatError {
+    UnknownField(String),
+    InvalidTemplate(String),
+}
+
+impl fmt::Display for FormatError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            FormatError::UnknownField(name) => write!(f, "unknown format field `{{{}}}`", name),
+            FormatError::InvalidTemplate(template) => {
+                write!(f, "could not parse format template `{}`", template)
+            }
+        }
+    }
+}
+
+#[derive(Clone, Debug, PartialEq)]
+enum TemplateToken<'a> {
+    Literal(&'a str),
+    Field(&'a str),
+    Group(Vec<TemplateToken<'a>>),
+}
+
+fn literal_token(input: &str) -> IResult<&str, TemplateToken<'_>> {
+    map(
+        take_till1(|c| matches!(c, '{' | '[' | ']')),
+        TemplateToken::Literal,
+    )(input)
+}
+
+fn field_token(input: &str) -> IResult<&str, TemplateToken<'_>> {
+    let field_name = take_while1(|c: char| c.is_alphanumeric() || c == '_');
+    map(delimited(char('{'), field_name, char('}')), TemplateToken::Field)(input)
+}
+
+fn group_token(input: &str) -> IResult<&str, TemplateToken<'_>> {
+    map(
+        delimited(char('['), template_tokens, char(']')),
+        TemplateToken::Group,
+    )(input)
+}
+
+fn template_tokens(input: &str) -> IResult<&str, Vec<TemplateToken<'_>>> {
+    many0(alt((field_token, group_token, literal_token)))(input)
+}
+
+fn tokens_to_segments(tokens: Vec<TemplateToken>) -> Result<Vec<LogSegment>, FormatError> {
+    tokens
+        .into_iter()
+        .map(|token| match token {
+            TemplateToken::Literal(s) => Ok(LogSegment::Literal(s.to_string())),
+            TemplateToken::Field(name) => LogSegment::from_field_name(name),
+            TemplateToken::Group(inner) => Ok(LogSegment::Group(tokens_to_segments(inner)?)),
+        })
+        .collect()
+}
+
+/// Parse a `--format` template into the ordered segments `render` walks.
+/// Unknown field names are reported as an error so a typo is caught at
+/// startup instead of silently being dropped.
+pub fn parse_template(template: &str) -> Result<Vec<LogSegment>, FormatError> {
+    let (rest, tokens) = template_tokens(template)
+        .map_err(|_| FormatError::InvalidTemplate(template.to_string()))?;
+    if !rest.is_empty() {
+        return Err(FormatError::InvalidTemplate(template.to_string()));
+    }
+    tokens_to_segments(tokens)
+}
+
+/// The fields available to a template, gathered from either the JSON decoder
+/// or the heuristic text path. Fields that don't apply to a given line
+/// (e.g. `pid` for a heuristic line) are `None` and render as empty.
+#[derive(Clone, Debug, Default)]
+pub struct LogFields {
+    pub level: Option<Level>,
+    pub timestamp: String,
+    pub source_line: Option<String>,
+    pub message: String,
+    pub pid: Option<usize>,
+    pub hostname: Option<String>,
+    pub type_: Option<String>,
+    pub stack: Option<String>,
+    pub errno: Option<String>,
+    pub syscall: Option<String>,
+    pub address: Option<String>,
+    pub port: Option<u16>,
+    pub secret: Option<String>,
+    pub v: Option<usize>,
+}
+
+fn level_label_and_color(level: Level) -> (&'static str, u8) {
+    match level {
+        Level::Error => ("ERROR", 1),
+        Level::Warn => ("WARN ", 3),
+        Level::Info => ("INFO ", 7),
+        Level::Debug => ("DEBUG", 4),
+        Level::Trace => ("TRACE", 5),
+    }
+}
+
+const EXTRAS_COLOR: u8 = 2;
+
+/// One rendered piece of a line: the literal text to print, and the ANSI
+/// color code (`\x1B[1;3{color}m`) it should be wrapped in, if any. Whether
+/// that color is actually applied is left to the output layer, which knows
+/// whether it's writing to a TTY or a plain file.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RenderedSegment {
+    pub text: String,
+    pub color: Option<u8>,
+    /// Set for the `{msg}` segment, so the output layer knows where it may
+    /// splice in `--highlight` matches.
+    pub is_message: bool,
+}
+
+fn plain(text: String) -> RenderedSegment {
+    RenderedSegment {
+        text,
+        color: None,
+        is_message: false,
+    }
+}
+
+fn colored(color: u8, text: String) -> RenderedSegment {
+    RenderedSegment {
+        text,
+        color: Some(color),
+        is_message: false,
+    }
+}
+
+/// Render one segment, returning the pieces it produced along with whether
+/// it was a field that actually had a value (as opposed to plain literal
+/// text) — `render`'s `Group` case uses that to decide whether the whole
+/// group, literals included, should be kept or dropped.
+fn render_segment(
+    segment: &LogSegment,
+    fields: &LogFields,
+    level_label: &str,
+    level_color: u8,
+) -> Option<(Vec<RenderedSegment>, bool)> {
+    match segment {
+        LogSegment::Literal(text) => Some((vec![plain(text.clone())], false)),
+        LogSegment::Group(inner) => {
+            let mut pieces = Vec::new();
+            let mut has_value = false;
+            for child in inner {
+                if let Some((child_pieces, child_has_value)) =
+                    render_segment(child, fields, level_label, level_color)
+                {
+                    has_value = has_value || child_has_value;
+                    pieces.extend(child_pieces);
+                }
+            }
+            has_value.then_some((pieces, true))
+        }
+        LogSegment::Level => Some((vec![colored(level_color, level_label.to_string())], true)),
+        LogSegment::Timestamp => Some((vec![colored(level_color, fields.timestamp.clone())], true)),
+        LogSegment::SourceLine => fields
+            .source_line
+            .as_ref()
+            .map(|line| (vec![colored(level_color, line.clone())], true)),
+        LogSegment::Message => Some((
+            vec![RenderedSegment {
+                text: fields.message.clone(),
+                color: None,
+                is_message: true,
+            }],
+            true,
+        )),
+        LogSegment::Pid => fields
+            .pid
+            .map(|pid| (vec![colored(EXTRAS_COLOR, pid.to_string())], true)),
+        LogSegment::Hostname => fields
+            .hostname
+            .as_ref()
+            .map(|hostname| (vec![colored(EXTRAS_COLOR, hostname.trim().to_string())], true)),
+        LogSegment::Type => fields
+            .type_
+            .as_ref()
+            .map(|type_| (vec![colored(EXTRAS_COLOR, type_.trim().to_string())], true)),
+        LogSegment::Stack => fields
+            .stack
+            .as_ref()
+            .map(|stack| (vec![colored(EXTRAS_COLOR, stack.trim().to_string())], true)),
+        LogSegment::Errno => fields
+            .errno
+            .as_ref()
+            .map(|errno| (vec![colored(EXTRAS_COLOR, errno.trim().to_string())], true)),
+        LogSegment::Syscall => fields
+            .syscall
+            .as_ref()
+            .map(|syscall| (vec![colored(EXTRAS_COLOR, syscall.trim().to_string())], true)),
+        LogSegment::Address => fields
+            .address
+            .as_ref()
+            .map(|address| (vec![colored(EXTRAS_COLOR, address.trim().to_string())], true)),
+        LogSegment::Port => fields
+            .port
+            .map(|port| (vec![colored(EXTRAS_COLOR, port.to_string())], true)),
+        LogSegment::Secret => fields
+            .secret
+            .as_ref()
+            .map(|secret| (vec![colored(EXTRAS_COLOR, secret.trim().to_string())], true)),
+        LogSegment::V => fields.v.map(|v| (vec![colored(EXTRAS_COLOR, v.to_string())], true)),
+    }
+}
+
+/// Render `fields` through `segments` into a sequence of colored text runs.
+/// This stops short of emitting ANSI escapes itself so that a file sink can
+/// write the same content without the escape-code noise.
+pub fn render(segments: &[LogSegment], fields: &LogFields) -> Vec<RenderedSegment> {
+    let (level_label, level_color) = match fields.level {
+        Some(level) => level_label_and_color(level),
+        None => ("INFO ", 7),
+    };
+
+    let mut out = Vec::with_capacity(segments.len());
+    for segment in segments {
+        if let Some((pieces, _)) = render_segment(segment, fields, level_label, level_color) {
+            out.extend(pieces);
+        }
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn rendered_text(segments: &[RenderedSegment]) -> String {
+        segments.iter().map(|s| s.text.as_str()).collect()
+    }
+
+    #[test]
+    fn parses_default_format() {
+        parse_template(DEFAULT_FORMAT).expect("default format must parse");
+    }
+
+    #[test]
+    fn field_names_allow_underscores() {
+        let segments = parse_template("{source_line}").expect("underscored field must parse");
+        assert_eq!(segments, vec![LogSegment::SourceLine]);
+    }
+
+    #[test]
+    fn unknown_field_is_an_error() {
+        let error = parse_template("{bogus}").unwrap_err();
+        assert_eq!(error, FormatError::UnknownField("bogus".to_string()));
+    }
+
+    #[test]
+    fn group_parses_into_a_nested_segment() {
+        let segments = parse_template("[{source_line}]").expect("group must parse");
+        assert_eq!(
+            segments,
+            vec![LogSegment::Group(vec![LogSegment::SourceLine])]
+        );
+    }
+
+    #[test]
+    fn group_is_dropped_entirely_when_its_field_is_absent() {
+        let segments = parse_template("x[ {source_line}]y").unwrap();
+        let fields = LogFields {
+            message: "hi".to_string(),
+            ..Default::default()
+        };
+        assert_eq!(rendered_text(&render(&segments, &fields)), "xy");
+    }
+
+    #[test]
+    fn group_is_kept_when_its_field_is_present() {
+        let segments = parse_template("x[ {source_line}]y").unwrap();
+        let fields = LogFields {
+            source_line: Some("main.rs:1".to_string()),
+            ..Default::default()
+        };
+        assert_eq!(rendered_text(&render(&segments, &fields)), "x main.rs:1y");
+    }
+
+    #[test]
+    fn default_format_omits_every_group_when_only_message_is_set() {
+        let segments = parse_template(DEFAULT_FORMAT).unwrap();
+        let fields = LogFields {
+            message: "hello".to_string(),
+            ..Default::default()
+        };
+        let text = rendered_text(&render(&segments, &fields));
+        assert!(!text.contains('['));
+        assert!(!text.contains(']'));
+        assert!(text.trim_end().ends_with("hello"));
+    }
+
+    #[test]
+    fn default_format_surfaces_extras_without_stray_brackets() {
+        let segments = parse_template(DEFAULT_FORMAT).unwrap();
+        let fields = LogFields {
+            v: Some(0),
+            pid: Some(1),
+            hostname: Some("h".to_string()),
+            message: "x".to_string(),
+            ..Default::default()
+        };
+        let text = rendered_text(&render(&segments, &fields));
+        assert!(!text.contains('['));
+        assert!(!text.contains(']'));
+        assert!(text.contains("v:0"));
+        assert!(text.contains("pid:1"));
+        assert!(text.contains("hostname:h"));
+        assert!(text.trim_end().ends_with('x'));
+    }
+}
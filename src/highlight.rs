@@ -0,0 +1,85 @@
+//! `--highlight <regex>` support: wraps every substring matching any
+//! supplied pattern in an inverse/bright ANSI sequence so an operator
+//! watching a live stream can spot call-IDs, IP addresses, or error codes
+//! without post-processing.
+
+use regex::{Regex, RegexSet};
+
+const HIGHLIGHT_ON: &str = "\x1B[1;7m";
+const HIGHLIGHT_OFF: &str = "\x1B[0m";
+
+/// The combined set of `--highlight` patterns, built once at startup.
+pub struct Highlighter {
+    set: RegexSet,
+    patterns: Vec<Regex>,
+}
+
+impl Highlighter {
+    pub fn new(patterns: &[String]) -> Result<Self, regex::Error> {
+        let set = RegexSet::new(patterns)?;
+        let patterns = patterns.iter().map(|p| Regex::new(p)).collect::<Result<_, _>>()?;
+        Ok(Highlighter { set, patterns })
+    }
+
+    /// Splice a highlight escape around every match span in `text`,
+    /// restoring the plain message color afterward. Overlapping spans from
+    /// different patterns are merged into one highlighted run.
+    pub fn apply(&self, text: &str) -> String {
+        if !self.set.is_match(text) {
+            return text.to_string();
+        }
+
+        let mut spans: Vec<(usize, usize)> = self
+            .patterns
+            .iter()
+            .flat_map(|pattern| pattern.find_iter(text).map(|m| (m.start(), m.end())))
+            .collect();
+        spans.sort_unstable();
+
+        let mut merged: Vec<(usize, usize)> = Vec::new();
+        for (start, end) in spans {
+            match merged.last_mut() {
+                Some((_, last_end)) if start <= *last_end => *last_end = (*last_end).max(end),
+                _ => merged.push((start, end)),
+            }
+        }
+
+        let mut out = String::with_capacity(text.len());
+        let mut cursor = 0;
+        for (start, end) in merged {
+            out.push_str(&text[cursor..start]);
+            out.push_str(HIGHLIGHT_ON);
+            out.push_str(&text[start..end]);
+            out.push_str(HIGHLIGHT_OFF);
+            cursor = end;
+        }
+        out.push_str(&text[cursor..]);
+        out
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn no_match_returns_text_unchanged() {
+        let highlighter = Highlighter::new(&["bogus".to_string()]).unwrap();
+        assert_eq!(highlighter.apply("hello world"), "hello world");
+    }
+
+    #[test]
+    fn wraps_a_single_match() {
+        let highlighter = Highlighter::new(&["world".to_string()]).unwrap();
+        let expected = format!("hello {}world{}", HIGHLIGHT_ON, HIGHLIGHT_OFF);
+        assert_eq!(highlighter.apply("hello world"), expected);
+    }
+
+    #[test]
+    fn overlapping_spans_from_different_patterns_merge_into_one_run() {
+        let highlighter =
+            Highlighter::new(&["foobar".to_string(), "bar".to_string()]).unwrap();
+        let expected = format!("{}foobar{}", HIGHLIGHT_ON, HIGHLIGHT_OFF);
+        assert_eq!(highlighter.apply("foobar"), expected);
+    }
+}
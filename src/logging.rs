@@ -0,0 +1,83 @@
+//! Logging setup for siplog's own internal diagnostics (not the SIP app
+//! lines it prints), and the per-type directive set that also drives
+//! `--filter` on the printed lines themselves.
+//!
+//! `-v` sets the overall verbosity; `--filter default=info,rtp=debug,sip=trace`
+//! layers per-target overrides on top of it, in the same style as
+//! `env_logger`'s `RUST_LOG` directives.
+
+use log::LevelFilter;
+
+/// One `target=level` entry from `--filter`. `target: None` is the
+/// `default=` entry used when nothing more specific matches.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Directive {
+    pub target: Option<String>,
+    pub level: LevelFilter,
+}
+
+/// Parse a `--filter` spec like `"default=info,rtp=debug,sip=trace"` into
+/// its directives.
+pub fn parse_directives(spec: &str) -> Result<Vec<Directive>, String> {
+    spec.split(',')
+        .map(|entry| {
+            let mut parts = entry.splitn(2, '=');
+            let target = parts.next().unwrap_or("").trim();
+            let level = parts
+                .next()
+                .ok_or_else(|| format!("directive `{}` is missing `=level`", entry))?
+                .trim();
+            let level = level
+                .parse::<LevelFilter>()
+                .map_err(|_| format!("unknown level `{}` in directive `{}`", level, entry))?;
+            let target = if target.is_empty() || target == "default" {
+                None
+            } else {
+                Some(target.to_string())
+            };
+            Ok(Directive { target, level })
+        })
+        .collect()
+}
+
+/// Pick the directive that governs `target`: an exact match if one exists,
+/// otherwise the `default=` entry, otherwise `None`.
+pub fn effective_level(directives: &[Directive], target: Option<&str>) -> Option<LevelFilter> {
+    let specific = target.and_then(|target| {
+        directives
+            .iter()
+            .find(|directive| directive.target.as_deref() == Some(target))
+    });
+    specific
+        .or_else(|| directives.iter().find(|directive| directive.target.is_none()))
+        .map(|directive| directive.level)
+}
+
+fn level_for_verbosity(verbosity: usize) -> LevelFilter {
+    match verbosity {
+        0 => LevelFilter::Warn,
+        1 => LevelFilter::Info,
+        2 => LevelFilter::Debug,
+        _ => LevelFilter::Trace,
+    }
+}
+
+/// Initialize `env_logger` at a level derived from `-v` occurrences, with
+/// `--filter` directives layered on top as per-module overrides. A `default=`
+/// directive replaces the `-v` level.
+pub fn from_verbosity(verbosity: usize, directives: &[Directive]) {
+    let level = level_for_verbosity(verbosity);
+    let mut builder = env_logger::Builder::new();
+    builder.filter_level(level);
+    for directive in directives {
+        match &directive.target {
+            Some(target) => {
+                builder.filter_module(target, directive.level);
+            }
+            None => {
+                builder.filter_level(directive.level);
+            }
+        }
+    }
+    builder.init();
+}
@@ -0,0 +1,125 @@
+//! Where rendered lines go: a colorized stdout stream, and optionally a
+//! plain-text file tee that rotates once it hits a size cap.
+//!
+//! `format::render` only decides *what* text belongs to which color class;
+//! this module decides whether that color is actually worth emitting for a
+//! given destination, so archived logs on disk stay grep-able.
+
+use crate::format::RenderedSegment;
+use crate::highlight::Highlighter;
+use std::fs::{File, OpenOptions};
+use std::io::{self, Seek, SeekFrom, Write};
+use std::path::PathBuf;
+
+/// Default ceiling for `--file-capacity` when a file sink is in use.
+pub const DEFAULT_FILE_CAPACITY: u64 = 64 * 1024;
+
+fn write_line(
+    out: &mut impl Write,
+    rendered: &[RenderedSegment],
+    colorize: bool,
+    highlighter: Option<&Highlighter>,
+) -> io::Result<()> {
+    for segment in rendered {
+        // Highlight escapes are only worth emitting alongside the rest of
+        // this line's colors; a plain (file) destination stays escape-free.
+        if colorize && segment.is_message {
+            if let Some(highlighter) = highlighter {
+                write!(out, "{}", highlighter.apply(&segment.text))?;
+                continue;
+            }
+        }
+        match segment.color {
+            Some(color) if colorize => write!(out, "\x1B[1;3{}m{}\x1B[0m", color, segment.text)?,
+            _ => write!(out, "{}", segment.text)?,
+        }
+    }
+    writeln!(out)
+}
+
+/// A plain-text file sink that rotates (truncates) once it would exceed
+/// `capacity` bytes, so archived siplog output never grows unbounded.
+struct FileSink {
+    file: File,
+    capacity: u64,
+    written: u64,
+}
+
+impl FileSink {
+    fn new(path: PathBuf, capacity: u64) -> io::Result<Self> {
+        let file = OpenOptions::new()
+            .create(true)
+            .append(true)
+            .open(path)?;
+        let written = file.metadata()?.len();
+        Ok(FileSink {
+            file,
+            capacity,
+            written,
+        })
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.set_len(0)?;
+        self.file.seek(SeekFrom::Start(0))?;
+        self.written = 0;
+        Ok(())
+    }
+
+    fn write_line(&mut self, rendered: &[RenderedSegment]) -> io::Result<()> {
+        let mut line = String::new();
+        for segment in rendered {
+            line.push_str(&segment.text);
+        }
+        line.push('\n');
+
+        if self.written + line.len() as u64 > self.capacity {
+            self.rotate()?;
+        }
+        self.file.write_all(line.as_bytes())?;
+        self.written += line.len() as u64;
+        Ok(())
+    }
+}
+
+/// Fans a rendered line out to stdout (colorized only when stdout is a TTY)
+/// and, if configured, to a size-bounded file with colors stripped.
+pub struct Output {
+    colorize_stdout: bool,
+    file: Option<FileSink>,
+    highlighter: Option<Highlighter>,
+}
+
+impl Output {
+    pub fn new(
+        file_path: Option<PathBuf>,
+        file_capacity: u64,
+        highlighter: Option<Highlighter>,
+    ) -> io::Result<Self> {
+        let file = file_path.map(|path| FileSink::new(path, file_capacity)).transpose()?;
+        Ok(Output {
+            colorize_stdout: atty::is(atty::Stream::Stdout),
+            file,
+            highlighter,
+        })
+    }
+
+    pub fn emit(&mut self, rendered: &[RenderedSegment]) {
+        let stdout = io::stdout();
+        let mut handle = stdout.lock();
+        if let Err(error) = write_line(
+            &mut handle,
+            rendered,
+            self.colorize_stdout,
+            self.highlighter.as_ref(),
+        ) {
+            log::error!("error writing to stdout: {}", error);
+        }
+
+        if let Some(file) = &mut self.file {
+            if let Err(error) = file.write_line(rendered) {
+                log::error!("error writing to output file: {}", error);
+            }
+        }
+    }
+}
@@ -0,0 +1,277 @@
+//! Producer/worker/collector pipeline for high-throughput piped input.
+//!
+//! A single reader thread splits stdin into fixed-size blocks (preserving a
+//! partial trailing line across block boundaries). A pool of worker threads
+//! decode and render each block's lines independently. A collector buffers
+//! finished blocks by sequence number and writes them out in original input
+//! order — block K is always emitted before block K+1, but workers make no
+//! other ordering guarantee among themselves.
+
+use crate::decoder::LineDecoder;
+use crate::filter::Filters;
+use crate::format::{self, LogSegment, RenderedSegment};
+use crate::output::Output;
+use log::Level;
+use std::collections::HashMap;
+use std::io::{self, Read};
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+
+const BLOCK_SIZE: usize = 64 * 1024;
+
+struct Block {
+    seq: u64,
+    lines: Vec<String>,
+}
+
+struct RenderedBlock {
+    seq: u64,
+    lines: Vec<Option<Vec<RenderedSegment>>>,
+}
+
+/// Buffers `RenderedBlock`s that arrive out of order and hands them back in
+/// sequence, one run of consecutive blocks at a time.
+#[derive(Default)]
+struct Reassembler {
+    next_seq: u64,
+    pending: HashMap<u64, RenderedBlock>,
+}
+
+impl Reassembler {
+    /// Accept a newly finished block and return every block, including this
+    /// one, that is now ready to be written out in order.
+    fn accept(&mut self, block: RenderedBlock) -> Vec<RenderedBlock> {
+        self.pending.insert(block.seq, block);
+        let mut ready = Vec::new();
+        while let Some(next) = self.pending.remove(&self.next_seq) {
+            self.next_seq += 1;
+            ready.push(next);
+        }
+        ready
+    }
+}
+
+/// Move as much of `carry_bytes` as is valid UTF-8 into a `String`, leaving
+/// any trailing incomplete sequence (e.g. the first byte of a 2-byte
+/// character whose second byte hasn't been read yet) in `carry_bytes` for
+/// the next call.
+fn decode_utf8_prefix(carry_bytes: &mut Vec<u8>) -> String {
+    let mut decoded = String::new();
+    loop {
+        let (valid_up_to, invalid_len) = match std::str::from_utf8(carry_bytes) {
+            Ok(_) => (carry_bytes.len(), None),
+            Err(error) => (error.valid_up_to(), error.error_len()),
+        };
+        decoded.push_str(std::str::from_utf8(&carry_bytes[..valid_up_to]).unwrap());
+        carry_bytes.drain(..valid_up_to);
+        // Bytes no later read can complete are replaced, and decoding goes on.
+        let len = match invalid_len {
+            Some(len) => len,
+            None => return decoded,
+        };
+        carry_bytes.drain(..len);
+        decoded.push('\u{FFFD}');
+    }
+}
+
+/// Split off and return every complete line currently in `carry`, leaving
+/// any partial trailing line for the next call.
+fn drain_lines(carry: &mut String) -> Vec<String> {
+    let mut lines = Vec::new();
+    while let Some(newline_index) = carry.find('\n') {
+        lines.push(carry[..newline_index].to_string());
+        carry.drain(..=newline_index);
+    }
+    lines
+}
+
+fn produce_blocks(tx: mpsc::SyncSender<Block>) {
+    let mut stdin = io::stdin();
+    let mut buf = vec![0u8; BLOCK_SIZE];
+    // Bytes read but not yet known to be valid UTF-8, e.g. a multi-byte
+    // character split across two reads. Held back until a read completes it.
+    let mut carry_bytes: Vec<u8> = Vec::new();
+    let mut carry = String::new();
+    let mut seq = 0u64;
+
+    loop {
+        let read = match stdin.read(&mut buf) {
+            Ok(0) => break,
+            Ok(n) => n,
+            Err(_) => break,
+        };
+        carry_bytes.extend_from_slice(&buf[..read]);
+        carry.push_str(&decode_utf8_prefix(&mut carry_bytes));
+
+        let lines = drain_lines(&mut carry);
+        if !lines.is_empty() {
+            if tx.send(Block { seq, lines }).is_err() {
+                return;
+            }
+            seq += 1;
+        }
+    }
+
+    if !carry_bytes.is_empty() {
+        // The stream ended mid-sequence; there's no further input to
+        // complete it, so fall back to lossy decoding for this tail only.
+        carry.push_str(&String::from_utf8_lossy(&carry_bytes));
+    }
+    if !carry.is_empty() {
+        let _ = tx.send(Block {
+            seq,
+            lines: vec![carry],
+        });
+    }
+}
+
+fn decode_and_render(
+    line: &str,
+    decoders: &[Box<dyn LineDecoder>],
+    filters: &Filters,
+    segments: &[LogSegment],
+) -> Option<Vec<RenderedSegment>> {
+    let line = line.trim();
+    for decoder in decoders {
+        if let Some(parsed) = decoder.try_decode(line) {
+            let level = parsed.level.unwrap_or(Level::Info);
+            if !filters.allows(level, parsed.pid, parsed.type_.as_deref()) {
+                return None;
+            }
+            return Some(format::render(segments, &parsed.into()));
+        }
+    }
+    None
+}
+
+fn worker_loop(
+    block_rx: Arc<Mutex<mpsc::Receiver<Block>>>,
+    result_tx: mpsc::Sender<RenderedBlock>,
+    decoders: Arc<Vec<Box<dyn LineDecoder>>>,
+    filters: Arc<Filters>,
+    segments: Arc<Vec<LogSegment>>,
+) {
+    loop {
+        let block = {
+            let block_rx = block_rx.lock().unwrap();
+            block_rx.recv()
+        };
+        let block = match block {
+            Ok(block) => block,
+            Err(_) => return,
+        };
+
+        let lines = block
+            .lines
+            .iter()
+            .map(|line| decode_and_render(line, &decoders, &filters, &segments))
+            .collect();
+
+        if result_tx.send(RenderedBlock { seq: block.seq, lines }).is_err() {
+            return;
+        }
+    }
+}
+
+/// Run the block-parallel pipeline to completion, writing rendered lines to
+/// `output` in original input order.
+pub fn run(
+    workers: usize,
+    decoders: Vec<Box<dyn LineDecoder>>,
+    filters: Filters,
+    segments: Vec<LogSegment>,
+    mut output: Output,
+) {
+    let decoders = Arc::new(decoders);
+    let filters = Arc::new(filters);
+    let segments = Arc::new(segments);
+
+    let (block_tx, block_rx) = mpsc::sync_channel::<Block>(workers * 2);
+    let block_rx = Arc::new(Mutex::new(block_rx));
+    let (result_tx, result_rx) = mpsc::channel::<RenderedBlock>();
+
+    let producer = thread::spawn(move || produce_blocks(block_tx));
+
+    let mut worker_handles = Vec::with_capacity(workers);
+    for _ in 0..workers {
+        let block_rx = Arc::clone(&block_rx);
+        let result_tx = result_tx.clone();
+        let decoders = Arc::clone(&decoders);
+        let filters = Arc::clone(&filters);
+        let segments = Arc::clone(&segments);
+        worker_handles.push(thread::spawn(move || {
+            worker_loop(block_rx, result_tx, decoders, filters, segments);
+        }));
+    }
+    drop(result_tx);
+
+    let mut reassembler = Reassembler::default();
+    for block in result_rx {
+        for ready in reassembler.accept(block) {
+            for line in ready.lines.into_iter().flatten() {
+                output.emit(&line);
+            }
+        }
+    }
+
+    let _ = producer.join();
+    for handle in worker_handles {
+        let _ = handle.join();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn decode_utf8_prefix_holds_back_split_multibyte_char() {
+        // "é" is 2 bytes (0xC3 0xA9); split it across two reads.
+        let mut carry_bytes = vec![b'a', 0xC3];
+        let decoded = decode_utf8_prefix(&mut carry_bytes);
+        assert_eq!(decoded, "a");
+        assert_eq!(carry_bytes, vec![0xC3]);
+
+        carry_bytes.push(0xA9);
+        let decoded = decode_utf8_prefix(&mut carry_bytes);
+        assert_eq!(decoded, "é");
+        assert!(carry_bytes.is_empty());
+    }
+
+    #[test]
+    fn drain_lines_keeps_trailing_partial_line() {
+        let mut carry = "one\ntwo\nthr".to_string();
+        let lines = drain_lines(&mut carry);
+        assert_eq!(lines, vec!["one".to_string(), "two".to_string()]);
+        assert_eq!(carry, "thr");
+    }
+
+    fn rendered_block(seq: u64) -> RenderedBlock {
+        RenderedBlock { seq, lines: vec![] }
+    }
+
+    #[test]
+    fn reassembler_buffers_out_of_order_blocks_until_contiguous() {
+        let mut reassembler = Reassembler::default();
+        assert!(reassembler.accept(rendered_block(1)).is_empty());
+        assert!(reassembler.accept(rendered_block(2)).is_empty());
+
+        let ready = reassembler.accept(rendered_block(0));
+        let seqs: Vec<u64> = ready.iter().map(|b| b.seq).collect();
+        assert_eq!(seqs, vec![0, 1, 2]);
+    }
+
+    #[test]
+    fn decode_and_render_trims_trailing_carriage_return() {
+        use crate::decoder::HeuristicDecoder;
+        use crate::format::LogSegment;
+
+        let decoders: Vec<Box<dyn LineDecoder>> = vec![Box::new(HeuristicDecoder)];
+        let filters = Filters::default();
+        let segments = vec![LogSegment::Message];
+
+        let rendered = decode_and_render("hello\r", &decoders, &filters, &segments)
+            .expect("HeuristicDecoder always matches");
+        assert_eq!(rendered[0].text, "hello");
+    }
+}